@@ -5,9 +5,11 @@ use std::sync::{
 
 use crate::{
     particle_sim::{SimState},
+    spatial_grid::{cell_size_for, SpatialGrid},
     Simulation,
 };
 use bevy::{prelude::*, sprite::MaterialMesh2dBundle, utils::HashMap};
+use rand::{rngs::StdRng, SeedableRng};
 use rand::prelude::*;
 
 #[derive(Component, Default)]
@@ -22,6 +24,18 @@ pub struct Size(pub u32);
 #[derive(Component, Default)]
 pub struct Mass(pub u32);
 
+/// Marks a ball whose starting `Transform` and `Velocity` came from an
+/// explicit [`crate::ExplicitBall`] entry rather than random generation, so
+/// `space_balls` leaves its position alone.
+#[derive(Component, Default)]
+pub struct ExplicitPlacement;
+
+/// Random number generator seeded from [`Simulation::seed`](crate::Simulation),
+/// so that every random draw made while setting up a run (ball sizes,
+/// masses, starting positions) is reproducible across runs.
+#[derive(Resource)]
+pub struct SimRng(pub StdRng);
+
 #[derive(Bundle, Default)]
 pub struct BallBundle {
     #[bundle]
@@ -36,6 +50,8 @@ pub struct BallPlugin;
 
 impl Plugin for BallPlugin {
     fn build(&self, app: &mut App) {
+        let seed = app.world.resource::<Simulation>().seed;
+        app.insert_resource(SimRng(StdRng::seed_from_u64(seed)));
         app.add_systems(
             (
                 create_balls,
@@ -55,13 +71,42 @@ fn create_balls(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     sim_params: Res<Simulation>,
+    mut rng: ResMut<SimRng>,
 ) {
-    for _ in 0..sim_params.num_balls {
-        let size = thread_rng().gen_range(sim_params.size_range.clone());
-        let mass = thread_rng().gen_range(sim_params.mass_range.clone());
+    for explicit in &sim_params.initial_balls {
+        commands.spawn((
+            BallBundle {
+                model: MaterialMesh2dBundle {
+                    mesh: meshes
+                        .add(Mesh::from(shape::Circle::new(explicit.size as f32)))
+                        .into(),
+                    material: materials.add(ColorMaterial::from(Color::Rgba {
+                        red: explicit.mass as f32,
+                        green: explicit.mass as f32,
+                        blue: explicit.mass as f32,
+                        alpha: 1.,
+                    })),
+                    transform: Transform::from_translation(explicit.position.extend(0.)),
+                    ..Default::default()
+                },
+                size: Size(explicit.size),
+                mass: Mass(explicit.mass),
+                vel: Velocity(explicit.velocity),
+                ..Default::default()
+            },
+            ExplicitPlacement,
+        ));
+    }
+
+    let remaining = sim_params
+        .num_balls
+        .saturating_sub(sim_params.initial_balls.len() as u32);
+    for _ in 0..remaining {
+        let size = rng.0.gen_range(sim_params.size_range.clone());
+        let mass = rng.0.gen_range(sim_params.mass_range.clone());
         let vel = Vec2::new(
-            thread_rng().gen_range(sim_params.velocity_range.clone()),
-            thread_rng().gen_range(sim_params.velocity_range.clone()),
+            rng.0.gen_range(sim_params.velocity_range.clone()),
+            rng.0.gen_range(sim_params.velocity_range.clone()),
         );
         commands.spawn(BallBundle {
             model: MaterialMesh2dBundle {
@@ -85,8 +130,9 @@ fn create_balls(
 }
 
 fn space_balls(
-    mut query: Query<(&mut Transform, &Size, Entity), With<Ball>>,
+    mut query: Query<(&mut Transform, &Size, Entity), (With<Ball>, Without<ExplicitPlacement>)>,
     mut camera: Query<(&Camera, &GlobalTransform)>,
+    mut rng: ResMut<SimRng>,
 ) {
     let (camera, camera_transform) = camera.get_single_mut().unwrap();
     let bounds = {
@@ -98,8 +144,8 @@ fn space_balls(
     };
     for (mut transform, size, _) in query.iter_mut() {
         transform.translation = Vec3 {
-            x: thread_rng().gen_range((bounds.0.x + size.0 as f32)..(bounds.1.x - size.0 as f32)),
-            y: thread_rng().gen_range((bounds.0.y + size.0 as f32)..(bounds.1.y - size.0 as f32)),
+            x: rng.0.gen_range((bounds.0.x + size.0 as f32)..(bounds.1.x - size.0 as f32)),
+            y: rng.0.gen_range((bounds.0.y + size.0 as f32)..(bounds.1.y - size.0 as f32)),
             ..Default::default()
         };
     }
@@ -111,6 +157,7 @@ fn pack_balls(
     mut camera: Query<(&Camera, &GlobalTransform)>,
     time: Res<Time>,
     mut state: ResMut<NextState<SimState>>,
+    sim_params: Res<Simulation>,
 ) {
     let (camera, camera_transform) = camera.get_single_mut().unwrap();
     let bounds = {
@@ -125,6 +172,8 @@ fn pack_balls(
     let touching: Mutex<HashMap<Entity, Vec3>> = Mutex::new(HashMap::new());
     let spaced = AtomicBool::new(false);
 
+    let cell_size = cell_size_for(query.iter().map(|(_, size, _)| size.0).max().unwrap_or(1));
+
     while !spaced.load(std::sync::atomic::Ordering::Relaxed) && !timer.finished() {
         for (entity, vel) in touching.lock().unwrap().drain() {
             let size = query.get_component::<Size>(entity).unwrap().0.to_owned();
@@ -141,13 +190,22 @@ fn pack_balls(
         spaced.store(true, std::sync::atomic::Ordering::Relaxed);
         timer.tick(time.raw_delta());
 
+        let mut grid = SpatialGrid::new(cell_size);
+        for (transform, _, entity) in query.iter() {
+            grid.insert(entity, transform.translation.truncate());
+        }
+
         query.par_iter().for_each(|(ball_pos1, size1, entity1)| {
-            query.par_iter().for_each(|(ball_pos2, size2, entity2)| {
+            for entity2 in grid.neighbors(ball_pos1.translation.truncate()) {
+                if entity2 == entity1 {
+                    continue;
+                }
+                let ball_pos2 = query.get_component::<Transform>(entity2).unwrap();
+                let size2 = query.get_component::<Size>(entity2).unwrap();
                 if ball_pos1
                     .translation
                     .distance_squared(ball_pos2.translation)
                     < ((size1.0 + size2.0).pow(2) as f32)
-                    && entity1 != entity2
                 {
                     spaced.store(true, std::sync::atomic::Ordering::Relaxed);
                     *touching
@@ -162,29 +220,46 @@ fn pack_balls(
                                     .distance_squared(ball_pos1.translation))
                                 .max(0.01));
                     touching.lock().unwrap().entry(entity1).and_modify(|v| {
+                        // A fresh RNG seeded from the two colliding entities
+                        // (rather than the shared `SimRng`) keeps this
+                        // deterministic regardless of which thread's closure
+                        // happens to run first, since `par_iter` gives no
+                        // guarantee about draw order from a single shared
+                        // stream.
+                        let mut nudge_rng = StdRng::seed_from_u64(
+                            sim_params.seed ^ entity1.to_bits() ^ entity2.to_bits(),
+                        );
                         *v = Vec3::new(
-                            thread_rng().gen_range(-1. ..1.),
-                            thread_rng().gen_range(-1. ..1.),
+                            nudge_rng.gen_range(-1. ..1.),
+                            nudge_rng.gen_range(-1. ..1.),
                             1.,
                         ) + v.clamp_length_max(10000.)
                     });
                 }
-            });
+            }
         });
     }
 
     let spaced = AtomicBool::new(false);
     let cleared_entites = Mutex::new(Vec::new());
     let num_cleared = AtomicI32::new(0);
+    let mut grid = SpatialGrid::new(cell_size);
+    for (transform, _, entity) in query.iter() {
+        grid.insert(entity, transform.translation.truncate());
+    }
     while !spaced.load(std::sync::atomic::Ordering::Relaxed) {
         spaced.store(true, std::sync::atomic::Ordering::Relaxed);
         query.par_iter().for_each(|(ball_pos1, size1, entity1)| {
-            query.par_iter().for_each(|(ball_pos2, size2, entity2)| {
+            for entity2 in grid.neighbors(ball_pos1.translation.truncate()) {
+                if entity2 == entity1 {
+                    continue;
+                }
+                let ball_pos2 = query.get_component::<Transform>(entity2).unwrap();
+                let size2 = query.get_component::<Size>(entity2).unwrap();
                 if ball_pos1
                     .translation
                     .distance_squared(ball_pos2.translation)
                     < ((size1.0 + size2.0).pow(2) as f32)
-                    && entity1 != entity2
                     && !cleared_entites.lock().unwrap().contains(&entity1)
                     && !cleared_entites.lock().unwrap().contains(&entity2)
                 {
@@ -197,7 +272,7 @@ fn pack_balls(
                     cleared_entites.lock().unwrap().push(entity2);
                     spaced.store(false, std::sync::atomic::Ordering::Relaxed);
                 }
-            });
+            }
         });
     }
     state.set(SimState::Simulate)
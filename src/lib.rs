@@ -1,14 +1,50 @@
 mod ball;
+mod integrator;
 mod particle_sim;
+mod quadtree;
+mod scene;
+mod spatial_grid;
 use bevy::prelude::*;
 use std::ops::Range;
 
+pub use particle_sim::{BallSnapshot, FlockingParams, FrameSnapshot, SnapshotBuffer};
+pub use scene::{ExplicitBall, SimulationLoadError};
+
 #[derive(Resource)]
 pub struct Simulation {
     num_balls: u32,
     size_range: Range<u32>,
     mass_range: Range<u32>,
     velocity_range: Range<f32>,
+    /// Gravitational constant `G` used for inter-particle attraction.
+    /// `0.` (the default) disables gravity entirely.
+    gravitational_constant: f32,
+    /// Barnes-Hut approximation threshold: a tree node is treated as a
+    /// single mass once `node_size / distance` drops below this.
+    theta: f32,
+    /// Boids-style flocking mode; see [`FlockingParams`].
+    flocking: FlockingParams,
+    /// Balls placed at explicit positions/velocities rather than randomly,
+    /// typically loaded from a scene file via [`Simulation::from_file`].
+    /// `create_balls`/`space_balls` spawn these first and fill the rest of
+    /// `num_balls` randomly.
+    initial_balls: Vec<ExplicitBall>,
+    /// Fixed timestep, in seconds, the collision/integration schedule runs
+    /// at regardless of frame rate.
+    fixed_dt: f32,
+    /// Number of continuous-collision substeps per fixed tick; higher
+    /// values catch fast bodies that would otherwise tunnel through each
+    /// other or the walls within a single tick.
+    substeps: u32,
+    /// Collision restitution coefficient: `1.` is perfectly elastic, `0.`
+    /// fully inelastic.
+    restitution: f32,
+    /// Seed for every random number drawn while setting up and running the
+    /// simulation, so a run can be reproduced exactly.
+    seed: u64,
+    /// Number of past [`FrameSnapshot`]s kept for scrubbing while paused.
+    /// `0` disables snapshotting.
+    snapshot_capacity: usize,
 }
 
 impl Default for Simulation {
@@ -18,23 +54,83 @@ impl Default for Simulation {
             size_range: 10..20,
             mass_range: 4..5,
             velocity_range: -1.0..1.0,
+            gravitational_constant: 0.,
+            theta: 0.5,
+            flocking: FlockingParams::default(),
+            initial_balls: Vec::new(),
+            fixed_dt: 1. / 60.,
+            substeps: 4,
+            restitution: 1.,
+            seed: 0,
+            snapshot_capacity: 600,
         }
     }
 }
 
 impl Simulation {
-    pub fn new(
-        num_balls: u32,
-        size_range: Range<u32>,
-        mass_range: Range<u32>,
-        velocity_range: Range<f32>,
-    ) -> Self {
-        Self {
-            num_balls,
-            size_range,
-            mass_range,
-            velocity_range,
-        }
+    pub fn with_num_balls(mut self, num_balls: u32) -> Self {
+        self.num_balls = num_balls;
+        self
+    }
+
+    pub fn with_size_range(mut self, size_range: Range<u32>) -> Self {
+        self.size_range = size_range;
+        self
+    }
+
+    pub fn with_mass_range(mut self, mass_range: Range<u32>) -> Self {
+        self.mass_range = mass_range;
+        self
+    }
+
+    pub fn with_velocity_range(mut self, velocity_range: Range<f32>) -> Self {
+        self.velocity_range = velocity_range;
+        self
+    }
+
+    /// Sets the gravitational constant `G` and the Barnes-Hut approximation
+    /// threshold together, since `theta` is meaningless without gravity.
+    pub fn with_gravity(mut self, gravitational_constant: f32, theta: f32) -> Self {
+        self.gravitational_constant = gravitational_constant;
+        self.theta = theta;
+        self
+    }
+
+    pub fn with_flocking(mut self, flocking: FlockingParams) -> Self {
+        self.flocking = flocking;
+        self
+    }
+
+    pub fn with_fixed_dt(mut self, fixed_dt: f32) -> Self {
+        self.fixed_dt = fixed_dt;
+        self
+    }
+
+    pub fn with_substeps(mut self, substeps: u32) -> Self {
+        self.substeps = substeps;
+        self
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_snapshot_capacity(mut self, snapshot_capacity: usize) -> Self {
+        self.snapshot_capacity = snapshot_capacity;
+        self
+    }
+
+    /// Loads simulation parameters from a TOML or RON scene file (picked by
+    /// the file's extension), so a run can be configured and reproduced
+    /// without recompiling. See [`scene::SceneFile`] for the schema.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self, SimulationLoadError> {
+        scene::load(path.as_ref())
     }
 
     pub fn simulate(self) {
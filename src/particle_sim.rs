@@ -1,12 +1,17 @@
-use std::{collections::BTreeMap, default, sync::Mutex};
+use std::collections::VecDeque;
 
-use bevy::{
-    ecs::query,
-    prelude::*,
-    tasks::{ParallelSlice, TaskPool},
-};
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
 use crate::ball::{self, Ball, Mass, Size, Velocity};
+use crate::integrator::{integrate_substep, BodyState};
+use crate::quadtree::{Body, Quadtree};
+use crate::spatial_grid::SpatialGrid;
+use crate::Simulation;
+
+/// Softening term added to squared distance in the gravity force so that
+/// near-coincident bodies don't produce a singular (infinite) force.
+const GRAVITY_SOFTENING: f32 = 1.;
 
 pub struct ParticleSim;
 
@@ -19,41 +24,284 @@ pub enum SimState {
     Stop,
 }
 
-#[derive(PartialEq, Eq)]
-pub enum CollisionType {
-    Wall(Wall),
-    Entity(Entity),
+/// Configurable weights for the boids-style flocking mode: when
+/// `enabled`, balls steer as a flock instead of bouncing off each other.
+#[derive(Clone, Copy, serde::Deserialize)]
+#[serde(default)]
+pub struct FlockingParams {
+    pub enabled: bool,
+    /// Radius within which another ball is considered a neighbor.
+    pub perception_radius: f32,
+    /// Neighbors closer than this contribute to separation.
+    pub separation_distance: f32,
+    pub separation_weight: f32,
+    pub alignment_weight: f32,
+    pub cohesion_weight: f32,
+    pub max_speed: f32,
 }
 
-impl Default for CollisionType {
+impl Default for FlockingParams {
     fn default() -> Self {
-        CollisionType::Wall(Wall::default())
+        Self {
+            enabled: false,
+            perception_radius: 100.,
+            separation_distance: 25.,
+            separation_weight: 1.5,
+            alignment_weight: 1.,
+            cohesion_weight: 1.,
+            max_speed: 4.,
+        }
     }
 }
 
-#[derive(PartialEq, Eq, Default)]
-pub enum Wall {
-    #[default]
-    North,
-    East,
-    West,
-    South,
+/// Set while a single-step advance has been requested (via `Right` arrow
+/// while paused); consumed by [`consume_step`] after the tick it gated runs.
+#[derive(Resource, Default)]
+struct StepOnce(bool);
+
+/// One ball's state at a captured tick, keyed by [`Entity::to_bits`] since
+/// `Entity` itself isn't `Serialize`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct BallSnapshot {
+    pub entity_bits: u64,
+    pub position: Vec3,
+    pub velocity: Vec2,
+}
+
+/// Every ball's state at one fixed tick, as recorded into a [`SnapshotBuffer`].
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FrameSnapshot {
+    pub balls: Vec<BallSnapshot>,
+}
+
+/// Ring buffer of the most recent [`FrameSnapshot`]s, enabling deterministic
+/// replay: scrubbing back through `frames` while paused, or saving the
+/// history to disk for later playback. `scrub_cursor` indexes into `frames`
+/// while scrubbing; `None` means the live (non-scrubbed) simulation state.
+#[derive(Resource)]
+pub struct SnapshotBuffer {
+    frames: VecDeque<FrameSnapshot>,
+    capacity: usize,
+    scrub_cursor: Option<usize>,
+}
+
+impl SnapshotBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            scrub_cursor: None,
+        }
+    }
+
+    fn push(&mut self, frame: FrameSnapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// Serializes the captured history to `path` as RON, so a run can be
+    /// replayed later outside the live simulation.
+    pub fn save_to_file(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let contents = ron::to_string(&self.frames.iter().collect::<Vec<_>>())
+            .expect("FrameSnapshot history should always be serializable");
+        std::fs::write(path, contents)
+    }
+}
+
+/// Appends the current tick's ball states to the [`SnapshotBuffer`], so it
+/// can be scrubbed while paused or saved to disk for replay.
+fn capture_snapshot(
+    query: Query<(&Transform, &Velocity, Entity), With<Ball>>,
+    mut snapshots: ResMut<SnapshotBuffer>,
+) {
+    let balls = query
+        .iter()
+        .map(|(transform, velocity, entity)| BallSnapshot {
+            entity_bits: entity.to_bits(),
+            position: transform.translation,
+            velocity: velocity.0,
+        })
+        .collect();
+    snapshots.push(FrameSnapshot { balls });
 }
 
 impl Plugin for ParticleSim {
     fn build(&self, app: &mut App) {
+        let fixed_dt = app.world.resource::<Simulation>().fixed_dt;
+        let snapshot_capacity = app.world.resource::<Simulation>().snapshot_capacity;
+        app.insert_resource(FixedTime::new_from_secs(fixed_dt));
+        app.insert_resource(StepOnce(false));
+        app.insert_resource(SnapshotBuffer::new(snapshot_capacity));
+
         app.add_state::<SimState>();
         app.add_plugin(crate::ball::BallPlugin);
         app.add_startup_system(setup);
-        app.add_system(collider.in_set(OnUpdate(SimState::Simulate)));
+        app.add_system(handle_sim_input);
+        app.add_system(
+            handle_pause_scrub
+                .run_if(|state: Res<State<SimState>>| *state.get() == SimState::Pause),
+        );
+        app.add_system(
+            apply_scrubbed_frame
+                .after(handle_pause_scrub)
+                .run_if(|state: Res<State<SimState>>| *state.get() == SimState::Pause),
+        );
+
+        // Gravity, collision/integration and flocking all run on the fixed
+        // schedule so every mode is frame-rate independent and each tick
+        // produces exactly one `capture_snapshot`, keeping replay
+        // reproducible regardless of which mode is active.
+        app.add_system(
+            apply_gravity
+                .in_schedule(CoreSchedule::FixedUpdate)
+                .run_if(should_simulate)
+                .before(collider),
+        );
+        app.add_system(
+            collider
+                .in_schedule(CoreSchedule::FixedUpdate)
+                .run_if(should_simulate)
+                .run_if(|sim_params: Res<Simulation>| !sim_params.flocking.enabled),
+        );
+        app.add_system(
+            flock
+                .in_schedule(CoreSchedule::FixedUpdate)
+                .run_if(should_simulate)
+                .run_if(|sim_params: Res<Simulation>| sim_params.flocking.enabled),
+        );
+        app.add_system(
+            capture_snapshot
+                .in_schedule(CoreSchedule::FixedUpdate)
+                .run_if(should_simulate)
+                .after(collider)
+                .after(flock),
+        );
+        app.add_system(
+            consume_step
+                .in_schedule(CoreSchedule::FixedUpdate)
+                .after(capture_snapshot),
+        );
     }
 }
 
-fn collider(
-    mut query: Query<(&mut Velocity, &mut Transform, &Size, &Mass, Entity), With<Ball>>,
-    time: Res<Time>,
+/// Whether the fixed-schedule gravity/collision systems should run this
+/// tick: normally only while [`SimState::Simulate`], but also for exactly
+/// one tick when [`StepOnce`] is set while paused, so a user can step
+/// through a collision frame by frame.
+fn should_simulate(state: Res<State<SimState>>, step_once: Res<StepOnce>) -> bool {
+    *state.get() == SimState::Simulate || (*state.get() == SimState::Pause && step_once.0)
+}
+
+/// Clears [`StepOnce`] once the tick it requested has run, so stepping
+/// advances exactly one fixed tick per key press.
+fn consume_step(mut step_once: ResMut<StepOnce>) {
+    step_once.0 = false;
+}
+
+/// Reads keyboard input to drive the [`SimState`] state machine: `Space`
+/// toggles between simulating and paused, and `Escape` stops the
+/// simulation. Stepping and scrubbing while paused are handled by
+/// [`handle_pause_scrub`] instead, since those also touch [`SnapshotBuffer`].
+fn handle_sim_input(
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<SimState>>,
+    mut next_state: ResMut<NextState<SimState>>,
+    mut snapshots: ResMut<SnapshotBuffer>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(SimState::Stop);
+        snapshots.scrub_cursor = None;
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Space) {
+        match state.get() {
+            SimState::Simulate => next_state.set(SimState::Pause),
+            SimState::Pause => {
+                next_state.set(SimState::Simulate);
+                // Leaving `Pause` always returns to the live simulation, so
+                // any in-progress scrub is discarded rather than continuing
+                // to overwrite the live state once `Simulate` resumes.
+                snapshots.scrub_cursor = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// While paused, `Left`/`Right` arrows scrub the [`SnapshotBuffer`]'s
+/// history: `Left` steps backward into the past, `Right` steps forward
+/// through scrubbed history and, once back at the live edge, requests a
+/// real [`StepOnce`] tick so the simulation can be advanced frame by frame.
+fn handle_pause_scrub(
+    keys: Res<Input<KeyCode>>,
+    mut snapshots: ResMut<SnapshotBuffer>,
+    mut step_once: ResMut<StepOnce>,
+) {
+    if keys.just_pressed(KeyCode::Left) {
+        let last = snapshots.frames.len().saturating_sub(1);
+        snapshots.scrub_cursor = Some(match snapshots.scrub_cursor {
+            Some(cursor) => cursor.saturating_sub(1),
+            None => last,
+        });
+    }
+
+    if keys.just_pressed(KeyCode::Right) {
+        match snapshots.scrub_cursor {
+            Some(cursor) if cursor + 1 < snapshots.frames.len() => {
+                snapshots.scrub_cursor = Some(cursor + 1);
+            }
+            _ => {
+                snapshots.scrub_cursor = None;
+                step_once.0 = true;
+            }
+        }
+    }
+}
+
+/// While scrubbing (`scrub_cursor` is `Some`), overwrites each ball's
+/// `Transform`/`Velocity` with the historical values from that snapshot
+/// frame so the scrubbed moment is what's rendered; otherwise a no-op, so
+/// the live simulation is left untouched.
+fn apply_scrubbed_frame(
+    snapshots: Res<SnapshotBuffer>,
+    mut query: Query<(&mut Transform, &mut Velocity, Entity), With<Ball>>,
+) {
+    let Some(cursor) = snapshots.scrub_cursor else {
+        return;
+    };
+    let Some(frame) = snapshots.frames.get(cursor) else {
+        return;
+    };
+
+    for ball in &frame.balls {
+        let entity = Entity::from_bits(ball.entity_bits);
+        if let Ok((mut transform, mut velocity, _)) = query.get_mut(entity) {
+            transform.translation = ball.position;
+            velocity.0 = ball.velocity;
+        }
+    }
+}
+
+/// Boids-style flocking: each ball steers toward the average heading and
+/// center of mass of its nearby neighbors while staying clear of ones that
+/// are too close, replacing the billiard-ball collision response while
+/// `sim_params.flocking.enabled` is set. Runs on the fixed schedule and
+/// scales its integration by `dt`, same as `apply_gravity`/`collider`, so
+/// motion is frame-rate independent and snapshots stay reproducible.
+fn flock(
+    mut query: Query<(&mut Velocity, &mut Transform, &Size, Entity), With<Ball>>,
+    sim_params: Res<Simulation>,
+    fixed_time: Res<FixedTime>,
     mut camera: Query<(&Camera, &GlobalTransform)>,
 ) {
+    let params = sim_params.flocking;
+    let dt = fixed_time.period.as_secs_f32();
     let (camera, camera_transform) = camera.get_single_mut().unwrap();
     let bounds = {
         let (min, max) = camera.logical_viewport_rect().unwrap();
@@ -66,203 +314,179 @@ fn collider(
                 .unwrap_or_default(),
         )
     };
-    let colliding = Mutex::new(Vec::new());
+
+    let mut grid = SpatialGrid::new(params.perception_radius.max(1.));
+    let mut neighbor_state: HashMap<Entity, (Vec2, Vec2)> = HashMap::new();
+    for (velocity, transform, _, entity) in query.iter() {
+        grid.insert(entity, transform.translation.truncate());
+        neighbor_state.insert(entity, (transform.translation.truncate(), velocity.0));
+    }
+
     query
-        .par_iter()
-        .for_each(|(ball_vel1, ball_pos1, ball_size1, ball_mass1, entity1)| {
-            query
-                .par_iter()
-                .for_each(|(ball_vel2, ball_pos2, ball_size2, ball_mass2, entity2)| {
-                    if ball_pos1
-                        .translation
-                        .distance_squared(ball_pos2.translation)
-                        < ((ball_size1.0 + ball_size2.0).pow(2) as f32)
-                        && entity1 != entity2
-                    {
-                        colliding
-                            .lock()
-                            .unwrap()
-                            .push((entity1, CollisionType::Entity(entity2)));
-                    } else {
-                        let (ballx, bally) = (ball_pos1.translation.x, ball_pos1.translation.y);
-
-                        if ballx > bounds.1.x - ball_size1.0 as f32 {
-                            colliding
-                                .lock()
-                                .unwrap()
-                                .push((entity1, CollisionType::Wall(Wall::East)));
-                            return;
-                        }
-
-                        if ballx < bounds.0.x + ball_size1.0 as f32 {
-                            colliding
-                                .lock()
-                                .unwrap()
-                                .push((entity1, CollisionType::Wall(Wall::West)));
-                            return;
-                        }
-
-                        if bally < bounds.0.y + ball_size1.0 as f32 {
-                            colliding
-                                .lock()
-                                .unwrap()
-                                .push((entity1, CollisionType::Wall(Wall::South)));
-                            return;
-                        }
-
-                        if bally > bounds.1.y - ball_size1.0 as f32 {
-                            colliding
-                                .lock()
-                                .unwrap()
-                                .push((entity1, CollisionType::Wall(Wall::North)));
-                            return;
-                        }
-                    }
-                })
-        });
+        .par_iter_mut()
+        .for_each_mut(|(mut velocity, mut transform, size, entity)| {
+            let position = transform.translation.truncate();
+
+            let mut separation = Vec2::ZERO;
+            let mut average_velocity = Vec2::ZERO;
+            let mut average_position = Vec2::ZERO;
+            let mut neighbor_count = 0;
 
-    let pool = TaskPool::new();
-    let query = Mutex::new(query);
-    colliding.lock().unwrap().dedup_by(|a, b| {
-        a.0 == b.0
-            || a.0
-                == match b.1 {
-                    CollisionType::Entity(entity) => entity,
-                    _ => b.0,
+            for neighbor in grid.neighbors(transform.translation.truncate()) {
+                if neighbor == entity {
+                    continue;
+                }
+                let (neighbor_position, neighbor_velocity) = neighbor_state[&neighbor];
+                let distance = position.distance(neighbor_position);
+                if distance <= 0. || distance > params.perception_radius {
+                    continue;
                 }
-                && match a.1 {
-                    CollisionType::Entity(entity) => entity,
-                    _ => a.0,
-                } == b.0
-            || a.1 == b.1
-    });
-    colliding
-        .lock()
-        .unwrap()
-        .par_splat_map(&pool, None, |chunk| {
-            for pair in chunk {
-                let entity1 = pair.0;
-                match &pair.1 {
-                    CollisionType::Wall(wall) => match wall {
-                        Wall::North => {
-                            let mut query = query.lock().unwrap();
-                            let vel = &mut query.get_component_mut::<Velocity>(entity1).unwrap().0;
-                            *vel = Vec2::new(vel.x, -vel.y.abs());
-                        }
-                        Wall::East => {
-                            let mut query = query.lock().unwrap();
-                            let vel = &mut query.get_component_mut::<Velocity>(entity1).unwrap().0;
-                            *vel = Vec2::new(-vel.x.abs(), vel.y);
-                        }
-                        Wall::West => {
-                            let mut query = query.lock().unwrap();
-                            let vel = &mut query.get_component_mut::<Velocity>(entity1).unwrap().0;
-                            *vel = Vec2::new(vel.x.abs(), vel.y);
-                        }
-                        Wall::South => {
-                            let mut query = query.lock().unwrap();
-                            let vel = &mut query.get_component_mut::<Velocity>(entity1).unwrap().0;
-
-                            *vel = Vec2::new(vel.x, vel.y.abs());
-                        }
-                    },
-                    CollisionType::Entity(entity2) => {
-                        let mut query = query.lock().unwrap();
-
-                        let size1 = query.get_component::<Size>(entity1).unwrap().0 as f32;
-                        let size2 =
-                            query.get_component::<Size>(entity2.to_owned()).unwrap().0 as f32;
-
-                        let mass1 = query.get_component::<Mass>(entity1).unwrap().0 as f32;
-                        let mass2 =
-                            query.get_component::<Mass>(entity2.to_owned()).unwrap().0 as f32;
-
-                        let position1 = query
-                            .get_component::<Transform>(entity1)
-                            .unwrap()
-                            .translation;
-                        let position2 = query
-                            .get_component::<Transform>(entity2.to_owned())
-                            .unwrap()
-                            .translation;
-
-                        let velocity1 = query.get_component::<Velocity>(entity1).unwrap().0;
-                        let velocity2 = query
-                            .get_component::<Velocity>(entity2.to_owned())
-                            .unwrap()
-                            .0;
-
-                        let dist_squared = position1
-                            .to_owned()
-                            .distance_squared(position2.to_owned())
-                            .max(1.);
-
-                        println!("{:?}", dist_squared - (size1 + size2).powi(2));
-
-                        let mass_scalar_1 = (2. * mass2) / (mass1 + mass2);
-                        let mass_scalar_2 = (2. * mass1) / (mass2 + mass1);
-
-                        let collision_normal_1 = (position1 - position2).truncate();
-                        let collision_normal_2 = (position2 - position1).truncate();
-
-                        let velocity_projection_1 = (velocity1 - velocity2).dot(collision_normal_1);
-                        let velocity_projection_2 = (velocity2 - velocity1).dot(collision_normal_2);
-
-                        let normalized_velocity_1 = mass_scalar_1 * velocity_projection_1
-                            / dist_squared
-                            * collision_normal_1;
-                        let normalized_velocity_2 = mass_scalar_2 * velocity_projection_2
-                            / dist_squared
-                            * collision_normal_2;
-
-                        let new_velocity_1 = velocity1 - normalized_velocity_1;
-                        let new_velocity_2 = velocity2 - normalized_velocity_2;
-
-                        query.get_component_mut::<Velocity>(entity1).unwrap().0 = new_velocity_1;
-                        query
-                            .get_component_mut::<Velocity>(entity2.to_owned())
-                            .unwrap()
-                            .0 = new_velocity_2;
-
-                        query
-                            .get_component_mut::<Transform>(entity1)
-                            .unwrap()
-                            .translation += (collision_normal_1.normalize_or_zero()
-                            * ((size1) - (collision_normal_1.length() * (size1 / (size1 + size2)))))
-                            .extend(1.);
-                        query
-                            .get_component_mut::<Transform>(entity2.to_owned())
-                            .unwrap()
-                            .translation += (collision_normal_2.normalize_or_zero()
-                            * ((size2) - (collision_normal_2.length() * (size2 / (size1 + size2)))))
-                            .extend(1.);
-                    }
-                };
+
+                if distance < params.separation_distance {
+                    separation += (position - neighbor_position) / distance;
+                }
+                average_velocity += neighbor_velocity;
+                average_position += neighbor_position;
+                neighbor_count += 1;
+            }
+
+            if neighbor_count > 0 {
+                average_velocity /= neighbor_count as f32;
+                average_position /= neighbor_count as f32;
+
+                let alignment = average_velocity - velocity.0;
+                let cohesion = average_position - position;
+
+                velocity.0 += dt
+                    * (separation * params.separation_weight
+                        + alignment * params.alignment_weight
+                        + cohesion * params.cohesion_weight);
+            }
+
+            if velocity.0.length() > params.max_speed {
+                velocity.0 = velocity.0.normalize_or_zero() * params.max_speed;
+            }
+
+            let next_position = position + velocity.0 * dt;
+            if next_position.x > bounds.1.x - size.0 as f32
+                || next_position.x < bounds.0.x + size.0 as f32
+            {
+                velocity.0.x = -velocity.0.x;
+            }
+            if next_position.y > bounds.1.y - size.0 as f32
+                || next_position.y < bounds.0.y + size.0 as f32
+            {
+                velocity.0.y = -velocity.0.y;
             }
+
+            transform.translation = (transform.translation + (velocity.0 * dt).extend(0.))
+                .clamp(
+                    (bounds.0 + Vec2::splat(size.0 as f32)).extend(1.),
+                    (bounds.1 - Vec2::splat(size.0 as f32)).extend(1.),
+                );
         });
+}
+
+/// Accumulates inter-particle gravitational attraction into each ball's
+/// `Velocity` ahead of the collision pass, approximating the all-pairs force
+/// with a Barnes-Hut quadtree so the cost stays close to O(n log n).
+fn apply_gravity(
+    mut query: Query<(&mut Velocity, &Transform, &Mass), With<Ball>>,
+    sim_params: Res<Simulation>,
+    fixed_time: Res<FixedTime>,
+    mut camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    if sim_params.gravitational_constant == 0. {
+        return;
+    }
+    let dt = fixed_time.period.as_secs_f32();
+
+    let (camera, camera_transform) = camera.get_single_mut().unwrap();
+    let bounds = {
+        let (min, max) = camera.logical_viewport_rect().unwrap();
+        (
+            camera
+                .viewport_to_world_2d(camera_transform, min)
+                .unwrap_or_default(),
+            camera
+                .viewport_to_world_2d(camera_transform, max)
+                .unwrap_or_default(),
+        )
+    };
+    let size = (bounds.1 - bounds.0).max_element();
+
+    let bodies: Vec<Body> = query
+        .iter()
+        .map(|(_, transform, mass)| Body {
+            position: transform.translation.truncate(),
+            mass: mass.0 as f32,
+        })
+        .collect();
+    let tree = Quadtree::build(&bodies, bounds.0, size);
 
     query
-        .lock()
-        .unwrap()
         .par_iter_mut()
-        .for_each_mut(|(ball_vel, mut ball_pos, size, _, _)| {
-            ball_pos.translation = (ball_pos.translation + ball_vel.0.extend(1.)).clamp(
-                (bounds.0 + Vec2::splat(size.0 as f32 - 0.1)).extend(1.),
-                (bounds.1 - Vec2::splat(size.0 as f32 - 0.1)).extend(1.),
-            );
+        .for_each_mut(|(mut velocity, transform, mass)| {
+            let body = Body {
+                position: transform.translation.truncate(),
+                mass: mass.0 as f32,
+            };
+            velocity.0 += dt
+                * tree.acceleration_on(
+                    body,
+                    sim_params.gravitational_constant,
+                    sim_params.theta,
+                    GRAVITY_SOFTENING,
+                );
         });
-    /*println!(
-        "{}",
-        query
-            .lock()
-            .unwrap()
-            .iter()
-            .map(
-                |(vel, _, _, mass, _)| ((1. / 2. * mass.0 as f32) * vel.0.x.powf(2.))
-                    + ((1. / 2. * mass.0 as f32) * vel.0.y.powf(2.))
-            )
-            .sum::<f32>()
-    );*/
+}
+
+/// Advances ball positions and resolves wall/ball-ball collisions for one
+/// fixed tick. Runs the whole tick in `sim_params.substeps` substeps, each
+/// using continuous collision detection (see [`crate::integrator`]) so fast
+/// balls can't tunnel through each other or the walls between checks, and
+/// a configurable `restitution` so collisions needn't be perfectly elastic.
+fn collider(
+    mut query: Query<(&mut Velocity, &mut Transform, &Size, &Mass, Entity), With<Ball>>,
+    fixed_time: Res<FixedTime>,
+    sim_params: Res<Simulation>,
+    mut camera: Query<(&Camera, &GlobalTransform)>,
+) {
+    let (camera, camera_transform) = camera.get_single_mut().unwrap();
+    let bounds = {
+        let (min, max) = camera.logical_viewport_rect().unwrap();
+        (
+            camera
+                .viewport_to_world_2d(camera_transform, min)
+                .unwrap_or_default(),
+            camera
+                .viewport_to_world_2d(camera_transform, max)
+                .unwrap_or_default(),
+        )
+    };
+
+    let entities: Vec<Entity> = query.iter().map(|(.., entity)| entity).collect();
+    let mut bodies: Vec<BodyState> = query
+        .iter()
+        .map(|(velocity, transform, size, mass, _)| BodyState {
+            position: transform.translation.truncate(),
+            velocity: velocity.0,
+            radius: size.0 as f32,
+            mass: mass.0 as f32,
+        })
+        .collect();
+
+    let substeps = sim_params.substeps.max(1);
+    let substep_dt = fixed_time.period.as_secs_f32() / substeps as f32;
+    for _ in 0..substeps {
+        integrate_substep(&mut bodies, bounds.0, bounds.1, substep_dt, sim_params.restitution);
+    }
+
+    for (entity, body) in entities.into_iter().zip(bodies) {
+        let (mut velocity, mut transform, ..) = query.get_mut(entity).unwrap();
+        velocity.0 = body.velocity;
+        transform.translation = body.position.extend(transform.translation.z);
+    }
 }
 
 fn setup(mut commands: Commands) {
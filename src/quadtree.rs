@@ -0,0 +1,241 @@
+use bevy::prelude::Vec2;
+
+/// Maximum subdivision depth before coincident/near-coincident bodies are
+/// merged into a single point mass instead of splitting forever.
+const MAX_DEPTH: u32 = 16;
+
+/// A point mass as seen by the quadtree: a world-space position and a mass.
+#[derive(Clone, Copy, Debug)]
+pub struct Body {
+    pub position: Vec2,
+    pub mass: f32,
+}
+
+enum Node {
+    Empty,
+    Leaf(Body),
+    Internal {
+        mass: f32,
+        center_of_mass: Vec2,
+        children: Box<[Node; 4]>,
+    },
+}
+
+/// A Barnes-Hut quadtree over a square region, used to approximate
+/// gravitational attraction between bodies in roughly O(n log n) instead of
+/// the O(n^2) cost of summing every pair directly.
+pub struct Quadtree {
+    root: Node,
+    size: f32,
+}
+
+impl Quadtree {
+    /// Builds a tree covering the square `min..min + Vec2::splat(size)`.
+    /// `size` should cover the full simulation bounds so every body falls
+    /// inside the root.
+    pub fn build(bodies: &[Body], min: Vec2, size: f32) -> Self {
+        let mut root = Node::Empty;
+        for &body in bodies {
+            insert(&mut root, body, min, size, 0);
+        }
+        Self { root, size }
+    }
+
+    /// Approximate acceleration on `body` from every other body in the tree.
+    /// A node spanning width `s` at distance `d` from `body` is treated as a
+    /// single mass at its center of mass when `s / d < theta`, otherwise its
+    /// children are visited individually. `softening` avoids a singularity
+    /// when `body` nearly coincides with another point mass (this also
+    /// makes `body`'s own contribution to itself vanish, since the distance
+    /// is then exactly zero).
+    pub fn acceleration_on(&self, body: Body, g: f32, theta: f32, softening: f32) -> Vec2 {
+        accumulate(&self.root, self.size, body, g, theta, softening)
+    }
+}
+
+fn quadrant_index(min: Vec2, size: f32, position: Vec2) -> usize {
+    let mid = min + Vec2::splat(size / 2.);
+    match (position.x >= mid.x, position.y >= mid.y) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (false, true) => 2,
+        (true, true) => 3,
+    }
+}
+
+fn quadrant_bounds(min: Vec2, size: f32, index: usize) -> (Vec2, f32) {
+    let half = size / 2.;
+    let offset = match index {
+        0 => Vec2::new(0., 0.),
+        1 => Vec2::new(half, 0.),
+        2 => Vec2::new(0., half),
+        _ => Vec2::new(half, half),
+    };
+    (min + offset, half)
+}
+
+fn insert(node: &mut Node, body: Body, min: Vec2, size: f32, depth: u32) {
+    match node {
+        Node::Empty => *node = Node::Leaf(body),
+        Node::Leaf(existing) => {
+            if depth >= MAX_DEPTH || size <= f32::EPSILON {
+                let mass = existing.mass + body.mass;
+                let position =
+                    (existing.position * existing.mass + body.position * body.mass) / mass;
+                *node = Node::Leaf(Body { position, mass });
+                return;
+            }
+
+            let existing = *existing;
+            let mut children = [Node::Empty, Node::Empty, Node::Empty, Node::Empty];
+            for b in [existing, body] {
+                let index = quadrant_index(min, size, b.position);
+                let (child_min, child_size) = quadrant_bounds(min, size, index);
+                insert(&mut children[index], b, child_min, child_size, depth + 1);
+            }
+            let (mass, center_of_mass) = aggregate(&children);
+            *node = Node::Internal {
+                mass,
+                center_of_mass,
+                children: Box::new(children),
+            };
+        }
+        Node::Internal {
+            mass,
+            center_of_mass,
+            children,
+        } => {
+            let index = quadrant_index(min, size, body.position);
+            let (child_min, child_size) = quadrant_bounds(min, size, index);
+            insert(&mut children[index], body, child_min, child_size, depth + 1);
+            let (new_mass, new_center_of_mass) = aggregate(children);
+            *mass = new_mass;
+            *center_of_mass = new_center_of_mass;
+        }
+    }
+}
+
+fn aggregate(children: &[Node; 4]) -> (f32, Vec2) {
+    let mut mass = 0.;
+    let mut weighted_position = Vec2::ZERO;
+    for child in children {
+        let (child_mass, child_center_of_mass) = match child {
+            Node::Empty => (0., Vec2::ZERO),
+            Node::Leaf(body) => (body.mass, body.position),
+            Node::Internal {
+                mass,
+                center_of_mass,
+                ..
+            } => (*mass, *center_of_mass),
+        };
+        mass += child_mass;
+        weighted_position += child_center_of_mass * child_mass;
+    }
+
+    if mass > 0. {
+        (mass, weighted_position / mass)
+    } else {
+        (0., Vec2::ZERO)
+    }
+}
+
+fn accumulate(node: &Node, size: f32, body: Body, g: f32, theta: f32, softening: f32) -> Vec2 {
+    match node {
+        Node::Empty => Vec2::ZERO,
+        Node::Leaf(other) => pull(body, other.position, other.mass, g, softening),
+        Node::Internal {
+            mass,
+            center_of_mass,
+            children,
+        } => {
+            let distance = body.position.distance(*center_of_mass);
+            if distance > 0. && size / distance < theta {
+                pull(body, *center_of_mass, *mass, g, softening)
+            } else {
+                children
+                    .iter()
+                    .map(|child| accumulate(child, size / 2., body, g, theta, softening))
+                    .sum()
+            }
+        }
+    }
+}
+
+/// Acceleration imparted on `body` by a point mass `other_mass` at
+/// `other_position`, i.e. `F / body.mass` with `F = g * m1 * m2 / (d^2 + eps^2)`.
+/// Coincident positions (including a body acting on itself) yield zero, since
+/// `delta` is zero and there is no direction to pull in, whatever `softening`.
+fn pull(body: Body, other_position: Vec2, other_mass: f32, g: f32, softening: f32) -> Vec2 {
+    let delta = other_position - body.position;
+    if delta == Vec2::ZERO {
+        return Vec2::ZERO;
+    }
+    let dist_squared = delta.length_squared() + softening * softening;
+    let direction = delta.normalize_or_zero();
+    let force = g * body.mass * other_mass / dist_squared;
+    direction * (force / body.mass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theta_zero_matches_exact_newtonian_acceleration() {
+        let a = Body {
+            position: Vec2::new(0., 0.),
+            mass: 2.,
+        };
+        let b = Body {
+            position: Vec2::new(10., 0.),
+            mass: 5.,
+        };
+        let tree = Quadtree::build(&[a, b], Vec2::new(-50., -50.), 100.);
+
+        // theta = 0. never lets a node approximate its children as a single
+        // mass (`size / distance < 0.` is never true), so this always
+        // recurses down to exact per-body contributions.
+        let acceleration = tree.acceleration_on(a, 1., 0., 0.);
+        let expected = b.mass / 10f32.powi(2);
+
+        assert!((acceleration.x - expected).abs() < 1e-4);
+        assert!(acceleration.y.abs() < 1e-4);
+    }
+
+    #[test]
+    fn body_does_not_attract_itself() {
+        let a = Body {
+            position: Vec2::new(3., 4.),
+            mass: 7.,
+        };
+        let tree = Quadtree::build(&[a], Vec2::new(-50., -50.), 100.);
+
+        assert_eq!(tree.acceleration_on(a, 1., 0.5, 1.), Vec2::ZERO);
+    }
+
+    #[test]
+    fn distant_bodies_accumulate_from_multiple_directions() {
+        let center = Body {
+            position: Vec2::new(0., 0.),
+            mass: 1.,
+        };
+        let bodies = [
+            center,
+            Body {
+                position: Vec2::new(20., 0.),
+                mass: 4.,
+            },
+            Body {
+                position: Vec2::new(-20., 0.),
+                mass: 4.,
+            },
+        ];
+        let tree = Quadtree::build(&bodies, Vec2::new(-50., -50.), 100.);
+
+        // Equal, opposite masses at equal distance pull with equal and
+        // opposite force, so the net acceleration on the body between them
+        // cancels out.
+        let acceleration = tree.acceleration_on(center, 1., 0., 0.);
+        assert!(acceleration.length() < 1e-4);
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use bevy::prelude::Vec2;
+use serde::Deserialize;
+
+use crate::{FlockingParams, Simulation};
+
+/// A ball placed at an explicit position/velocity instead of a random one,
+/// as authored in a scene file. See [`Simulation::from_file`].
+#[derive(Clone, Copy, Deserialize)]
+pub struct ExplicitBall {
+    pub position: Vec2,
+    pub size: u32,
+    pub mass: u32,
+    pub velocity: Vec2,
+}
+
+/// On-disk schema for [`Simulation::from_file`]. Ranges are written as
+/// `(min, max)` pairs since `serde` can't derive `Range<T>` directly.
+#[derive(Deserialize)]
+pub struct SceneFile {
+    pub num_balls: u32,
+    pub size_range: (u32, u32),
+    pub mass_range: (u32, u32),
+    pub velocity_range: (f32, f32),
+    #[serde(default)]
+    pub gravitational_constant: f32,
+    #[serde(default = "default_theta")]
+    pub theta: f32,
+    #[serde(default)]
+    pub flocking: FlockingParams,
+    #[serde(default)]
+    pub initial_balls: Vec<ExplicitBall>,
+    #[serde(default = "default_fixed_dt")]
+    pub fixed_dt: f32,
+    #[serde(default = "default_substeps")]
+    pub substeps: u32,
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_snapshot_capacity")]
+    pub snapshot_capacity: usize,
+}
+
+fn default_theta() -> f32 {
+    0.5
+}
+
+fn default_fixed_dt() -> f32 {
+    1. / 60.
+}
+
+fn default_substeps() -> u32 {
+    4
+}
+
+fn default_restitution() -> f32 {
+    1.
+}
+
+fn default_snapshot_capacity() -> usize {
+    600
+}
+
+/// Above this, a Barnes-Hut node's diagonal (`s * sqrt(2)`) can exceed the
+/// distance at which it's selected (`s / theta`), so a node containing the
+/// body being evaluated can be approximated as a single mass and produce a
+/// spurious self-attraction term. See [`crate::quadtree`].
+const MAX_THETA: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Errors that can occur while loading a [`Simulation`] from a scene file.
+#[derive(Debug)]
+pub enum SimulationLoadError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Ron(ron::de::SpannedError),
+    /// The file's extension isn't `.toml` or `.ron`, so the format is ambiguous.
+    UnknownExtension,
+    /// A `(min, max)` range field had `min >= max`, which would later panic
+    /// in `rand::Rng::gen_range`.
+    InvalidRange(&'static str),
+    /// `theta` was outside `0. ..= MAX_THETA`, which would corrupt the
+    /// Barnes-Hut gravity approximation rather than just being slower/faster.
+    InvalidTheta(f32),
+}
+
+impl std::fmt::Display for SimulationLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read scene file: {err}"),
+            Self::Toml(err) => write!(f, "failed to parse TOML scene file: {err}"),
+            Self::Ron(err) => write!(f, "failed to parse RON scene file: {err}"),
+            Self::UnknownExtension => {
+                write!(f, "scene file must have a .toml or .ron extension")
+            }
+            Self::InvalidRange(field) => {
+                write!(f, "{field} must have min < max")
+            }
+            Self::InvalidTheta(theta) => {
+                write!(f, "theta must be between 0 and {MAX_THETA} (got {theta})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimulationLoadError {}
+
+impl From<std::io::Error> for SimulationLoadError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SimulationLoadError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Toml(err)
+    }
+}
+
+impl From<ron::de::SpannedError> for SimulationLoadError {
+    fn from(err: ron::de::SpannedError) -> Self {
+        Self::Ron(err)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Simulation, SimulationLoadError> {
+    let contents = std::fs::read_to_string(path)?;
+    let scene: SceneFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents)?,
+        Some("ron") => ron::from_str(&contents)?,
+        _ => return Err(SimulationLoadError::UnknownExtension),
+    };
+
+    if scene.size_range.0 >= scene.size_range.1 {
+        return Err(SimulationLoadError::InvalidRange("size_range"));
+    }
+    if scene.mass_range.0 >= scene.mass_range.1 {
+        return Err(SimulationLoadError::InvalidRange("mass_range"));
+    }
+    if scene.velocity_range.0 >= scene.velocity_range.1 {
+        return Err(SimulationLoadError::InvalidRange("velocity_range"));
+    }
+    if !(0. ..=MAX_THETA).contains(&scene.theta) {
+        return Err(SimulationLoadError::InvalidTheta(scene.theta));
+    }
+
+    Ok(Simulation {
+        num_balls: scene.num_balls,
+        size_range: scene.size_range.0..scene.size_range.1,
+        mass_range: scene.mass_range.0..scene.mass_range.1,
+        velocity_range: scene.velocity_range.0..scene.velocity_range.1,
+        gravitational_constant: scene.gravitational_constant,
+        theta: scene.theta,
+        flocking: scene.flocking,
+        initial_balls: scene.initial_balls,
+        fixed_dt: scene.fixed_dt,
+        substeps: scene.substeps,
+        restitution: scene.restitution,
+        seed: scene.seed,
+        snapshot_capacity: scene.snapshot_capacity,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_toml_maps_tuple_ranges_to_ranges() {
+        let path = write_temp(
+            "bevy_2d_particle_sim_test_load.toml",
+            r#"
+                num_balls = 5
+                size_range = [10, 20]
+                mass_range = [1, 3]
+                velocity_range = [-2.0, 2.0]
+            "#,
+        );
+        let simulation = load(&path);
+        std::fs::remove_file(&path).ok();
+        let simulation = simulation.unwrap();
+
+        assert_eq!(simulation.num_balls, 5);
+        assert_eq!(simulation.size_range, 10..20);
+        assert_eq!(simulation.mass_range, 1..3);
+        assert_eq!(simulation.velocity_range, -2.0..2.0);
+    }
+
+    #[test]
+    fn load_ron_is_picked_by_extension() {
+        let path = write_temp(
+            "bevy_2d_particle_sim_test_load.ron",
+            r#"(
+                num_balls: 5,
+                size_range: (10, 20),
+                mass_range: (1, 3),
+                velocity_range: (-2.0, 2.0),
+            )"#,
+        );
+        let simulation = load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(simulation.unwrap().num_balls, 5);
+    }
+
+    #[test]
+    fn load_rejects_unknown_extension() {
+        let path = write_temp("bevy_2d_particle_sim_test_load.yaml", "num_balls: 5");
+        let result = load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(matches!(result, Err(SimulationLoadError::UnknownExtension)));
+    }
+
+    #[test]
+    fn load_rejects_backwards_range() {
+        let path = write_temp(
+            "bevy_2d_particle_sim_test_backwards_range.toml",
+            r#"
+                num_balls = 5
+                size_range = [20, 10]
+                mass_range = [1, 3]
+                velocity_range = [-2.0, 2.0]
+            "#,
+        );
+        let result = load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(matches!(
+            result,
+            Err(SimulationLoadError::InvalidRange("size_range"))
+        ));
+    }
+
+    #[test]
+    fn load_rejects_theta_above_safe_threshold() {
+        let path = write_temp(
+            "bevy_2d_particle_sim_test_bad_theta.toml",
+            r#"
+                num_balls = 5
+                size_range = [10, 20]
+                mass_range = [1, 3]
+                velocity_range = [-2.0, 2.0]
+                theta = 0.9
+            "#,
+        );
+        let result = load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(matches!(result, Err(SimulationLoadError::InvalidTheta(_))));
+    }
+}
@@ -0,0 +1,57 @@
+use bevy::{prelude::Vec2, utils::HashMap};
+
+/// Uniform spatial hash used to turn broad-phase checks from O(n^2) into
+/// roughly O(n) for uniformly distributed bodies. Each cell holds the keys
+/// whose position currently falls inside it; candidates for a given position
+/// are its own cell plus the eight neighbors. Generic over the key so it can
+/// bucket ECS `Entity`s (see `ball`/`particle_sim`) or plain body indices
+/// (see [`crate::integrator`]) alike.
+pub struct SpatialGrid<K> {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<K>>,
+}
+
+impl<K: Copy> SpatialGrid<K> {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, position: Vec2) {
+        self.cells
+            .entry(Self::cell(position, self.cell_size))
+            .or_default()
+            .push(key);
+    }
+
+    /// Keys sharing the cell `position` falls in, plus its eight neighbors.
+    pub fn neighbors(&self, position: Vec2) -> impl Iterator<Item = K> + '_ {
+        let (cx, cy) = Self::cell(position, self.cell_size);
+        (-1..=1)
+            .flat_map(move |dx| (-1..=1).map(move |dy| (cx + dx, cy + dy)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+
+    fn cell(position: Vec2, cell_size: f32) -> (i32, i32) {
+        (
+            (position.x / cell_size).floor() as i32,
+            (position.y / cell_size).floor() as i32,
+        )
+    }
+}
+
+/// Cell size recommended for a population whose largest body has radius
+/// `max_radius`: twice that radius, so any pair of touching bodies shares or
+/// neighbors a cell regardless of where in the cell they sit.
+pub fn cell_size_for_radius(max_radius: f32) -> f32 {
+    max_radius.max(f32::EPSILON) * 2.
+}
+
+/// As [`cell_size_for_radius`], for the integer ball sizes used in `ball.rs`.
+pub fn cell_size_for(max_size: u32) -> f32 {
+    cell_size_for_radius(max_size as f32)
+}